@@ -5,16 +5,35 @@ use simplelog::{LevelFilter, WriteLogger};
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
-use subprocess::ExitStatus::*;
 
 use mailproc::*;
 
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(StructOpt, Debug)]
 struct Opt {
+    /// Path to the config file to load. Defaults to ~/.mailproc.conf.
+    /// A `.sieve` extension loads the file as a Sieve script instead of
+    /// the TOML rule format.
+    #[structopt(short = "c", long = "config")]
+    config: Option<PathBuf>,
+
     /// Test configuration and exit
     #[structopt(short = "t", long = "test")]
     test: bool,
+
+    /// Run fixture-driven rule tests against a directory of sample .eml files and exit
+    #[structopt(long = "test-dir")]
+    test_dir: Option<PathBuf>,
+
+    /// Load the config through a watcher that reloads it when the file changes
+    /// (validating before swapping in). The message is handled against the
+    /// config as loaded at startup: this process handles one message from
+    /// stdin and exits, so later edits of the file do not reach it.
+    #[structopt(long = "watch")]
+    watch: bool,
 }
 
 fn init_log() {
@@ -40,17 +59,38 @@ fn main() {
 
 fn run() -> i32 {
     let opt = Opt::from_args();
-    let mut conf = match dirs_next::home_dir() {
-        Some(path) => path,
-        _ => PathBuf::from(""),
+    let conf = match opt.config {
+        Some(ref path) => path.clone(),
+        None => {
+            let mut conf = match dirs_next::home_dir() {
+                Some(path) => path,
+                _ => PathBuf::from(""),
+            };
+            conf.push(".mailproc.conf");
+            conf
+        }
     };
-    conf.push(".mailproc.conf");
-    let config = match Config::load_from_path(conf) {
-        Ok(config) => config,
-        Err(e) => {
-            error!("Colud not read config: {}", e);
-            return 1;
+
+    let watcher = if opt.watch {
+        match watcher::ConfigWatcher::spawn(conf.clone(), WATCH_POLL_INTERVAL) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                error!("Colud not read config: {}", e);
+                return 1;
+            }
         }
+    } else {
+        None
+    };
+    let config = match &watcher {
+        Some(watcher) => watcher.current(),
+        None => match Config::load_auto_from_path(&conf) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Colud not read config: {}", e);
+                return 1;
+            }
+        },
     };
 
     init_log();
@@ -66,6 +106,17 @@ fn run() -> i32 {
         return 0;
     }
 
+    if let Some(ref test_dir) = opt.test_dir {
+        return if testing::run_test_dir(test_dir, &config) { 0 } else { 1 };
+    }
+
+    process_stdin(&config)
+}
+
+/// Reads a single message from stdin, handles it against `config` and exits.
+/// This is the only invocation model mailproc supports today: one process
+/// per message, as wired up by an MDA like procmail.
+fn process_stdin(config: &Config) -> i32 {
     let mut input_buf = Vec::<u8>::new();
     match std::io::stdin().read_to_end(&mut input_buf) {
         Ok(_) => (),
@@ -74,34 +125,30 @@ fn run() -> i32 {
             return 2;
         }
     }
-    let parsed_mail = match mailparse::parse_mail(&input_buf) {
+    handle_message(&input_buf, config);
+    0
+}
+
+fn handle_message(input_buf: &[u8], config: &Config) {
+    let parsed_mail = match mailparse::parse_mail(input_buf) {
         Ok(m) => m,
         Err(e) => {
             error!("Could not parse mail: {}", e);
-            return 3;
+            return;
         }
     };
 
-    if let Some((rule, buffer)) = handle(parsed_mail, &input_buf, config) {
-        if let Some(ref actions) = rule.action {
-            for action in actions {
+    if let Some((outcome, mut buffer)) = handle(&parsed_mail, input_buf, config) {
+        let actions = outcome.actions();
+        if actions.is_empty() {
+            info!("No action, message dropped");
+        } else {
+            for action in &actions {
                 info!("Doing action: {}", action.join(" "));
-                let job = Job::run(&action, Some(&buffer));
-                info!(
-                    "Result: {}",
-                    match job.subprocess.exit_status() {
-                        Some(Exited(code)) => format!("Exited: {}", code),
-                        Some(Signaled(code)) => format!("Signaled: {}", code),
-                        Some(Other(code)) => format!("Other: {}", code),
-                        Some(Undetermined) => "Undetermined".to_string(),
-                        None => "None".to_string(),
-                    }
-                );
+                let (success, new_buffer) = execute_action(action, &buffer);
+                buffer = new_buffer;
+                info!("Result: {}", if success { "ok" } else { "failed" });
             }
-        } else {
-            info!("No action, message dropped");
         }
     }
-    
-    0
 }
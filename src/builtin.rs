@@ -0,0 +1,290 @@
+//! First-class built-in actions, recognized when `action[0]` is a reserved
+//! `@`-prefixed verb. These run in-process instead of spawning a shell
+//! command, which lets mailproc deliver mail (e.g. to a Maildir) without
+//! depending on external tools like `procmail`/`maildrop`. The one
+//! exception is `@redirect`, which hands the message to `sendmail`.
+
+use log::*;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use subprocess::{Popen, PopenConfig, Redirection};
+
+static DELIVERY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Reserved `@`-prefixed verbs that `execute` handles itself, kept in
+/// one place so `Config::test()` can recognize them too instead of treating
+/// them as missing external programs.
+pub const BUILTIN_VERBS: &[&str] = &["@fileinto", "@discard", "@tag", "@redirect"];
+
+pub fn is_builtin_verb(verb: &str) -> bool {
+    BUILTIN_VERBS.contains(&verb)
+}
+
+/// Returns `Some((success, new_buffer))` if `action[0]` is a recognized
+/// built-in verb, or `None` if the caller should fall back to running it
+/// as a shell command via `Job::run`.
+pub fn execute(action: &[String], buffer: &[u8]) -> Option<(bool, Vec<u8>)> {
+    let verb = action.first()?;
+    match verb.as_str() {
+        "@fileinto" => Some(fileinto(action, buffer)),
+        "@discard" => {
+            info!("Discarding message");
+            Some((true, buffer.to_vec()))
+        }
+        "@tag" => Some(tag(action, buffer)),
+        "@redirect" => Some(redirect(action, buffer)),
+        _ => None,
+    }
+}
+
+fn unique_maildir_filename() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let counter = DELIVERY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}.M{}P{}.mailproc", secs, counter, std::process::id())
+}
+
+fn fileinto(action: &[String], buffer: &[u8]) -> (bool, Vec<u8>) {
+    let path = match action.get(1) {
+        Some(p) => p,
+        None => {
+            error!("@fileinto requires a maildir path argument");
+            return (false, buffer.to_vec());
+        }
+    };
+
+    let maildir = Path::new(path);
+    for sub in &["tmp", "new", "cur"] {
+        if let Err(e) = fs::create_dir_all(maildir.join(sub)) {
+            error!("Could not create maildir directory {}/{}: {}", path, sub, e);
+            return (false, buffer.to_vec());
+        }
+    }
+
+    let filename = unique_maildir_filename();
+    let tmp_path = maildir.join("tmp").join(&filename);
+    let new_path = maildir.join("new").join(&filename);
+
+    let success = File::create(&tmp_path)
+        .and_then(|mut f| f.write_all(buffer))
+        .and_then(|_| fs::rename(&tmp_path, &new_path))
+        .map_err(|e| error!("Could not deliver to maildir {}: {}", path, e))
+        .is_ok();
+
+    (success, buffer.to_vec())
+}
+
+/// Splits `buffer` into the raw header bytes and the raw body bytes at the
+/// blank-line boundary, without touching the body bytes at all: a non-UTF-8
+/// attachment must come back out byte-for-byte identical.
+fn split_header_body(buffer: &[u8]) -> (&[u8], &[u8]) {
+    let (header_end, sep_len) = match find_subslice(buffer, b"\r\n\r\n") {
+        Some(i) => (i, 4),
+        None => match find_subslice(buffer, b"\n\n") {
+            Some(i) => (i, 2),
+            None => (buffer.len(), 0),
+        },
+    };
+    (&buffer[..header_end], &buffer[(header_end + sep_len).min(buffer.len())..])
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Like `str::strip_prefix`, but matches `prefix` case-insensitively, since
+/// header field names are case-insensitive (RFC 5322 §2.2) and mail clients
+/// write `X-Keywords:` in varying case.
+fn strip_prefix_ignore_case<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = line.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&line[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn tag(action: &[String], buffer: &[u8]) -> (bool, Vec<u8>) {
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    for spec in &action[1..] {
+        if let Some(keyword) = spec.strip_prefix('+') {
+            add.push(keyword.to_string());
+        } else if let Some(keyword) = spec.strip_prefix('-') {
+            remove.push(keyword.to_string());
+        } else {
+            error!("Invalid @tag argument {:?}, expected +keyword or -keyword", spec);
+            return (false, buffer.to_vec());
+        }
+    }
+
+    let (header_bytes, body) = split_header_body(buffer);
+    let headers = match std::str::from_utf8(header_bytes) {
+        Ok(h) => h,
+        Err(e) => {
+            error!("@tag: message headers are not valid UTF-8: {}", e);
+            return (false, buffer.to_vec());
+        }
+    };
+
+    let mut keywords: Vec<String> = headers
+        .lines()
+        .find_map(|line| strip_prefix_ignore_case(line, "X-Keywords:"))
+        .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    for keyword in add {
+        if !keywords.contains(&keyword) {
+            keywords.push(keyword);
+        }
+    }
+    keywords.retain(|k| !remove.contains(k));
+
+    let mut new_headers: Vec<&str> = headers
+        .lines()
+        .filter(|line| strip_prefix_ignore_case(line, "X-Keywords:").is_none())
+        .collect();
+    let keywords_header = format!("X-Keywords: {}", keywords.join(", "));
+    new_headers.push(&keywords_header);
+
+    let mut rebuilt = new_headers.join("\r\n").into_bytes();
+    rebuilt.extend_from_slice(b"\r\n\r\n");
+    rebuilt.extend_from_slice(body);
+
+    (true, rebuilt)
+}
+
+/// Hands the message off to the system MTA for delivery to `addr`. Unlike
+/// `fileinto`/`tag`, a Sieve `redirect` fundamentally requires a hop through
+/// something that can actually route mail to an arbitrary address, so this
+/// shells out to `sendmail` the same way a traditional procmail recipe would
+/// rather than reimplementing an SMTP client in-process.
+fn redirect(action: &[String], buffer: &[u8]) -> (bool, Vec<u8>) {
+    let addr = match action.get(1) {
+        Some(a) => a,
+        None => {
+            error!("@redirect requires a recipient address argument");
+            return (false, buffer.to_vec());
+        }
+    };
+
+    let spawned = Popen::create(
+        &["sendmail", "-i", addr],
+        PopenConfig {
+            stdin: Redirection::Pipe,
+            stdout: Redirection::Pipe,
+            stderr: Redirection::Pipe,
+            ..Default::default()
+        },
+    );
+    let mut p = match spawned {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Could not spawn sendmail to redirect to {}: {}", addr, e);
+            return (false, buffer.to_vec());
+        }
+    };
+    if let Err(e) = p.communicate_bytes(Some(buffer)) {
+        error!("Could not hand message to sendmail for {}: {}", addr, e);
+        return (false, buffer.to_vec());
+    }
+    let _ = p.wait();
+    let success = p.exit_status().map(|s| s.success()).unwrap_or(false);
+    if !success {
+        error!("sendmail exited unsuccessfully while redirecting to {}", addr);
+    }
+    (success, buffer.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mailproc-builtin-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).expect("could not create scratch dir");
+        dir
+    }
+
+    #[test]
+    fn fileinto_delivers_to_a_maildir() {
+        let maildir = scratch_dir("fileinto");
+        let action = vec!["@fileinto".to_string(), maildir.to_str().unwrap().to_string()];
+        let (success, buffer) = fileinto(&action, b"Subject: hi\r\n\r\nbody");
+        assert!(success);
+        assert_eq!(buffer, b"Subject: hi\r\n\r\nbody");
+
+        let delivered: Vec<_> = fs::read_dir(maildir.join("new")).unwrap().collect();
+        assert_eq!(delivered.len(), 1);
+        let contents = fs::read(delivered[0].as_ref().unwrap().path()).unwrap();
+        assert_eq!(contents, b"Subject: hi\r\n\r\nbody");
+
+        fs::remove_dir_all(&maildir).ok();
+    }
+
+    #[test]
+    fn fileinto_round_trips_a_non_utf8_body() {
+        let maildir = scratch_dir("fileinto-binary");
+        let mut message = b"Subject: attachment\r\n\r\n".to_vec();
+        message.extend_from_slice(&[0xff, 0x00, 0xfe, 0x80, 0x01]);
+
+        let action = vec!["@fileinto".to_string(), maildir.to_str().unwrap().to_string()];
+        let (success, buffer) = fileinto(&action, &message);
+        assert!(success);
+        assert_eq!(buffer, message);
+
+        let delivered: Vec<_> = fs::read_dir(maildir.join("new")).unwrap().collect();
+        let contents = fs::read(delivered[0].as_ref().unwrap().path()).unwrap();
+        assert_eq!(contents, message);
+
+        fs::remove_dir_all(&maildir).ok();
+    }
+
+    #[test]
+    fn tag_adds_and_removes_keywords() {
+        let action = vec!["@tag".to_string(), "+seen".to_string(), "+work".to_string()];
+        let (success, buffer) = tag(&action, b"Subject: hi\r\n\r\nbody");
+        assert!(success);
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("X-Keywords: seen, work"));
+        assert!(text.ends_with("\r\n\r\nbody"));
+
+        let action = vec!["@tag".to_string(), "-seen".to_string()];
+        let (success, buffer) = tag(&action, text.as_bytes());
+        assert!(success);
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("X-Keywords: work"));
+        assert!(!text.contains("seen"));
+    }
+
+    #[test]
+    fn tag_matches_existing_keywords_header_case_insensitively() {
+        let action = vec!["@tag".to_string(), "+urgent".to_string()];
+        let (success, buffer) = tag(&action, b"Subject: hi\r\nx-keywords: work\r\n\r\nbody");
+        assert!(success);
+        let text = String::from_utf8(buffer).unwrap();
+
+        // Only one X-Keywords header should remain, merging the existing
+        // lower-case one rather than appending a duplicate.
+        assert_eq!(text.matches("eywords:").count(), 1);
+        assert!(text.contains("work"));
+        assert!(text.contains("urgent"));
+    }
+
+    #[test]
+    fn tag_preserves_a_non_utf8_body() {
+        let mut message = b"Subject: hi\r\n\r\n".to_vec();
+        message.extend_from_slice(&[0xff, 0x00, 0xfe]);
+
+        let action = vec!["@tag".to_string(), "+seen".to_string()];
+        let (success, buffer) = tag(&action, &message);
+        assert!(success);
+        assert!(buffer.ends_with(&[0xff, 0x00, 0xfe]));
+    }
+}
@@ -0,0 +1,376 @@
+//! A tiny parser for the subset of shell syntax needed to let an `action`
+//! express a pipeline (`a | b | c`) and redirections (`> file`, `>> file`,
+//! `2>&1`, `< file`), instead of requiring every multi-step action to be
+//! wrapped in `sh -c "..."`.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use subprocess::{Popen, PopenConfig, Redirection};
+
+#[derive(Debug, Clone, PartialEq)]
+enum RedirectMode {
+    In,
+    Out { append: bool },
+    Merge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Redirect {
+    fd: u8,
+    mode: RedirectMode,
+    target: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    argv: Vec<String>,
+    redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pipeline parse error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A single `action` entry is a pipeline spec if it is a single string
+/// (rather than a pre-split argv) and contains a pipe or redirection
+/// operator.
+pub fn is_pipeline_spec(action: &[String]) -> bool {
+    action.len() == 1 && action[0].contains(['|', '>', '<'])
+}
+
+fn split_words(spec: &str) -> Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+                i += 1;
+            }
+            '\'' | '"' => {
+                let quote = c;
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    current.push(chars[i]);
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(ParseError("unterminated quote".to_string()));
+                }
+                i += 1;
+                in_word = true;
+            }
+            '|' | '<' | '>' => {
+                // A digit-only word directly preceding `>`/`<` is an fd
+                // prefix (e.g. the `2` in `2>&1`), not a separate argv word;
+                // fold it into the operator token instead of flushing it.
+                let mut op = if in_word && !current.is_empty() && current.chars().all(|d| d.is_ascii_digit()) {
+                    in_word = false;
+                    std::mem::take(&mut current)
+                } else {
+                    if in_word {
+                        words.push(std::mem::take(&mut current));
+                        in_word = false;
+                    }
+                    String::new()
+                };
+                op.push(c);
+                i += 1;
+                if c == '>' && chars.get(i) == Some(&'>') {
+                    op.push('>');
+                    i += 1;
+                }
+                words.push(op);
+            }
+            '&' => {
+                current.push(c);
+                in_word = true;
+                i += 1;
+            }
+            _ => {
+                current.push(c);
+                in_word = true;
+                i += 1;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+/// Parses a pipeline spec like `foo -x | bar > out.txt 2>&1` into its
+/// stages. Redirections attach to the stage whose argv they trail.
+pub fn parse(spec: &str) -> Result<Pipeline, ParseError> {
+    let tokens = split_words(spec)?;
+    let mut stages = Vec::new();
+    let mut argv = Vec::new();
+    let mut redirects = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let tok = &tokens[i];
+        match tok.as_str() {
+            "|" => {
+                if argv.is_empty() {
+                    return Err(ParseError("empty pipeline stage".to_string()));
+                }
+                stages.push(Stage {
+                    argv: std::mem::take(&mut argv),
+                    redirects: std::mem::take(&mut redirects),
+                });
+                i += 1;
+            }
+            "<" | ">" | ">>" => {
+                let (fd, mode) = match tok.as_str() {
+                    "<" => (0, RedirectMode::In),
+                    ">" => (1, RedirectMode::Out { append: false }),
+                    ">>" => (1, RedirectMode::Out { append: true }),
+                    _ => unreachable!(),
+                };
+                let target = tokens.get(i + 1).ok_or_else(|| ParseError(format!("expected target after {}", tok)))?;
+                redirects.push(Redirect { fd, mode, target: target.clone() });
+                i += 2;
+            }
+            word if word.ends_with('>') && word[..word.len() - 1].chars().all(|c| c.is_ascii_digit()) => {
+                let fd: u8 = word[..word.len() - 1]
+                    .parse()
+                    .map_err(|_| ParseError(format!("invalid fd in {:?}", word)))?;
+                let target = tokens.get(i + 1).ok_or_else(|| ParseError(format!("expected target after {}", tok)))?;
+                if let Some(dup) = target.strip_prefix('&') {
+                    let dup_fd: u8 = dup.parse().map_err(|_| ParseError(format!("invalid dup target {:?}", target)))?;
+                    if dup_fd != 1 {
+                        return Err(ParseError("only 2>&1 is supported".to_string()));
+                    }
+                    redirects.push(Redirect { fd, mode: RedirectMode::Merge, target: String::new() });
+                } else {
+                    redirects.push(Redirect {
+                        fd,
+                        mode: RedirectMode::Out { append: false },
+                        target: target.clone(),
+                    });
+                }
+                i += 2;
+            }
+            word if word.ends_with(">>") && word[..word.len() - 2].chars().all(|c| c.is_ascii_digit()) => {
+                let fd: u8 = word[..word.len() - 2]
+                    .parse()
+                    .map_err(|_| ParseError(format!("invalid fd in {:?}", word)))?;
+                let target = tokens.get(i + 1).ok_or_else(|| ParseError(format!("expected target after {}", tok)))?;
+                redirects.push(Redirect { fd, mode: RedirectMode::Out { append: true }, target: target.clone() });
+                i += 2;
+            }
+            _ => {
+                argv.push(tok.clone());
+                i += 1;
+            }
+        }
+    }
+
+    if argv.is_empty() && stages.is_empty() {
+        return Err(ParseError("empty pipeline".to_string()));
+    }
+    if !argv.is_empty() {
+        stages.push(Stage { argv, redirects });
+    }
+
+    Ok(Pipeline { stages })
+}
+
+fn open_redirect_file(redirect: &Redirect) -> Result<File, std::io::Error> {
+    match redirect.mode {
+        RedirectMode::In => File::open(&redirect.target),
+        RedirectMode::Out { append } => OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&redirect.target),
+        RedirectMode::Merge => unreachable!("Merge redirects don't open a file"),
+    }
+}
+
+/// `(success, stdout, stderr)` captured from the last stage of a pipeline.
+pub type PipelineOutput = (bool, Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// Runs the pipeline, feeding `input` to the first stage's stdin (unless
+/// overridden by an explicit `<` redirect) and capturing the last stage's
+/// stdout/stderr, exactly like a single-stage `Job::run` would.
+pub fn run(pipeline: &Pipeline, input: Option<&[u8]>) -> Result<PipelineOutput, Box<dyn std::error::Error>> {
+    let mut processes: Vec<Popen> = Vec::with_capacity(pipeline.stages.len());
+    let mut next_stdin = if input.is_some() { Redirection::Pipe } else { Redirection::None };
+
+    for (idx, stage) in pipeline.stages.iter().enumerate() {
+        let is_last = idx == pipeline.stages.len() - 1;
+        let mut stdin = std::mem::replace(&mut next_stdin, Redirection::None);
+        let mut stdout = Redirection::Pipe;
+        let mut stderr = Redirection::Pipe;
+
+        for redirect in &stage.redirects {
+            match (redirect.fd, &redirect.mode) {
+                (0, RedirectMode::In) => stdin = Redirection::File(open_redirect_file(redirect)?),
+                (1, RedirectMode::Merge) => stdout = Redirection::Merge,
+                (2, RedirectMode::Merge) => stderr = Redirection::Merge,
+                (1, _) => stdout = Redirection::File(open_redirect_file(redirect)?),
+                (2, _) => stderr = Redirection::File(open_redirect_file(redirect)?),
+                _ => {}
+            }
+        }
+
+        let mut p = Popen::create(
+            &stage.argv,
+            PopenConfig {
+                stdin,
+                stdout,
+                stderr,
+                ..Default::default()
+            },
+        )?;
+
+        // Hand this stage's parent-side stdout pipe straight to the next
+        // stage's stdin, so the two processes are connected by the kernel
+        // without the parent shuttling bytes between them.
+        if !is_last {
+            if let Some(out) = p.stdout.take() {
+                next_stdin = Redirection::File(out);
+            }
+        }
+
+        processes.push(p);
+    }
+
+    // Only the first stage's stdin (our `input`) and the last stage's
+    // stdout/stderr are ever read by the parent; everything in between is
+    // wired up at the OS level above.
+    let (stdout, stderr) = if processes.len() == 1 {
+        processes[0].communicate_bytes(input)?
+    } else {
+        processes.first_mut().expect("non-empty pipeline").communicate_bytes(input)?;
+        processes.last_mut().expect("non-empty pipeline").communicate_bytes(None)?
+    };
+
+    for p in processes.iter_mut() {
+        let _ = p.wait();
+    }
+
+    let success = processes
+        .iter_mut()
+        .all(|p| p.exit_status().map(|s| s.success()).unwrap_or(false));
+
+    Ok((success, stdout, stderr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_pipeline_spec_requires_a_single_string_with_an_operator() {
+        assert!(is_pipeline_spec(&["a | b".to_string()]));
+        assert!(is_pipeline_spec(&["a > out.txt".to_string()]));
+        assert!(!is_pipeline_spec(&["plain-argv".to_string()]));
+        assert!(!is_pipeline_spec(&["a".to_string(), "b".to_string()]));
+    }
+
+    #[test]
+    fn split_words_handles_quotes_and_operators() {
+        let words = split_words(r#"foo 'a b' "c|d" | bar"#).unwrap();
+        assert_eq!(words, vec!["foo", "a b", "c|d", "|", "bar"]);
+    }
+
+    #[test]
+    fn split_words_keeps_fd_prefix_attached_to_the_operator() {
+        // The `2` in `2>&1` is an fd number, not a separate argv word.
+        assert_eq!(split_words("echo hi 2>&1").unwrap(), vec!["echo", "hi", "2>", "&1"]);
+        assert_eq!(split_words("echo hi 2>>log").unwrap(), vec!["echo", "hi", "2>>", "log"]);
+        assert_eq!(split_words("a >> out.txt").unwrap(), vec!["a", ">>", "out.txt"]);
+    }
+
+    #[test]
+    fn split_words_rejects_unterminated_quote() {
+        assert!(split_words("foo 'unterminated").is_err());
+    }
+
+    #[test]
+    fn parse_builds_stages_and_redirects() {
+        let pipeline = parse("a -x | b > out.txt").unwrap();
+        assert_eq!(pipeline.stages.len(), 2);
+        assert_eq!(pipeline.stages[0].argv, vec!["a", "-x"]);
+        assert_eq!(pipeline.stages[1].argv, vec!["b"]);
+        assert_eq!(
+            pipeline.stages[1].redirects,
+            vec![Redirect {
+                fd: 1,
+                mode: RedirectMode::Out { append: false },
+                target: "out.txt".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_handles_stderr_merge_and_append() {
+        let pipeline = parse("echo hi 2>&1 >> out.txt").unwrap();
+        assert_eq!(pipeline.stages.len(), 1);
+        assert_eq!(
+            pipeline.stages[0].redirects,
+            vec![
+                Redirect { fd: 2, mode: RedirectMode::Merge, target: String::new() },
+                Redirect { fd: 1, mode: RedirectMode::Out { append: true }, target: "out.txt".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_empty_stages() {
+        assert!(parse("a | | b").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn run_pipes_data_between_stages() {
+        let pipeline = parse("cat | tr a-z A-Z").unwrap();
+        let (success, stdout, _) = run(&pipeline, Some(b"hello")).unwrap();
+        assert!(success);
+        assert_eq!(stdout, Some(b"HELLO".to_vec()));
+    }
+
+    #[test]
+    fn run_merges_stderr_into_stdout() {
+        let pipeline = parse("echo hi 2>&1").unwrap();
+        let (success, stdout, stderr) = run(&pipeline, None).unwrap();
+        assert!(success);
+        assert_eq!(stdout, Some(b"hi\n".to_vec()));
+        assert_eq!(stderr, None);
+    }
+
+    #[test]
+    fn run_redirects_stdout_to_a_file() {
+        let path = std::env::temp_dir().join(format!("mailproc-pipeline-test-{}", std::process::id()));
+        let pipeline = parse(&format!("echo hi > {}", path.display())).unwrap();
+        let (success, stdout, _) = run(&pipeline, None).unwrap();
+        assert!(success);
+        assert_eq!(stdout, None);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+        std::fs::remove_file(&path).ok();
+    }
+}
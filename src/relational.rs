@@ -0,0 +1,247 @@
+//! Relational and date match types for header rules (RFC 5231 / RFC 5260).
+//!
+//! A `headers` rule value can carry a comparator prefix instead of being a
+//! plain regex, e.g. `":gt 1000000"` or `":date year ge 2020"`. When a
+//! comparator prefix is present the header value is parsed as the
+//! appropriate type (integer or date) and compared with the given operator;
+//! otherwise callers should fall back to treating the value as a regex, so
+//! existing configs keep working unchanged.
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Comparator {
+    fn parse(s: &str) -> Option<Comparator> {
+        match s {
+            "eq" => Some(Comparator::Eq),
+            "ne" => Some(Comparator::Ne),
+            "gt" => Some(Comparator::Gt),
+            "ge" => Some(Comparator::Ge),
+            "lt" => Some(Comparator::Lt),
+            "le" => Some(Comparator::Le),
+            _ => None,
+        }
+    }
+
+    fn apply<T: PartialOrd>(self, a: T, b: T) -> bool {
+        match self {
+            Comparator::Eq => a == b,
+            Comparator::Ne => a != b,
+            Comparator::Gt => a > b,
+            Comparator::Ge => a >= b,
+            Comparator::Lt => a < b,
+            Comparator::Le => a <= b,
+        }
+    }
+}
+
+/// Whether a header rule value carries a relational/date comparator prefix,
+/// as opposed to being a plain regex.
+pub fn is_relational(value: &str) -> bool {
+    value.trim_start().starts_with(':')
+}
+
+/// Checks that a `:<comparator> <value>` or `:date ...` spec is at least
+/// syntactically well-formed (known comparator, parseable operand), the way
+/// `Config::test()` already checks that a plain regex header value compiles.
+/// This can't validate a `:date` spec's *comparand* against a real header
+/// value (there isn't one at `--test` time), so a spec that passes here can
+/// still legitimately fail to match any particular message.
+pub fn validate(spec: &str) -> Result<(), String> {
+    let spec = spec.trim_start().trim_start_matches(':');
+    let mut parts = spec.splitn(2, char::is_whitespace);
+    match parts.next() {
+        Some("date") => validate_date(parts.next().unwrap_or("").trim()),
+        Some(cmp_word) => {
+            Comparator::parse(cmp_word).ok_or_else(|| format!("unknown comparator {:?}", cmp_word))?;
+            let operand = parts.next().unwrap_or("").trim();
+            operand
+                .parse::<i64>()
+                .map(|_| ())
+                .map_err(|_| format!("expected an integer operand, got {:?}", operand))
+        }
+        None => Err("empty relational spec".to_string()),
+    }
+}
+
+fn validate_date(rest: &str) -> Result<(), String> {
+    let mut parts = rest.splitn(2, char::is_whitespace);
+    let first = parts.next().unwrap_or("");
+    let remainder = parts.next().unwrap_or("").trim();
+
+    match first {
+        "year" | "month" | "day" | "hour" | "minute" | "second" | "weekday" => {
+            let mut parts = remainder.splitn(2, char::is_whitespace);
+            let cmp_word = parts.next().unwrap_or("");
+            Comparator::parse(cmp_word).ok_or_else(|| format!("unknown comparator {:?}", cmp_word))?;
+            let operand = parts.next().unwrap_or("").trim();
+            operand
+                .parse::<i64>()
+                .map(|_| ())
+                .map_err(|_| format!("expected an integer operand, got {:?}", operand))
+        }
+        cmp_word => {
+            Comparator::parse(cmp_word).ok_or_else(|| format!("unknown comparator {:?}", cmp_word))?;
+            NaiveDate::parse_from_str(remainder, "%Y-%m-%d")
+                .map(|_| ())
+                .map_err(|e| format!("could not parse {:?} as a %Y-%m-%d date: {}", remainder, e))
+        }
+    }
+}
+
+/// Evaluates a `:<comparator> <value>` or `:date ...` spec against a header
+/// value. Returns `false` (rather than erroring) if the spec or the header
+/// value can't be parsed as the expected type, matching how a malformed
+/// regex already fails a rule instead of crashing `handle()`.
+pub fn evaluate(spec: &str, header_value: &str) -> bool {
+    let spec = spec.trim_start().trim_start_matches(':');
+    let mut parts = spec.splitn(2, char::is_whitespace);
+    match parts.next() {
+        Some("date") => evaluate_date(parts.next().unwrap_or("").trim(), header_value),
+        Some(cmp_word) => evaluate_numeric(cmp_word, parts.next().unwrap_or("").trim(), header_value),
+        None => false,
+    }
+}
+
+fn evaluate_numeric(cmp_word: &str, operand: &str, header_value: &str) -> bool {
+    let comparator = match Comparator::parse(cmp_word) {
+        Some(c) => c,
+        None => return false,
+    };
+    let operand: i64 = match operand.trim().parse() {
+        Ok(n) => n,
+        Err(_) => return false,
+    };
+    let value: i64 = match header_value.trim().parse() {
+        Ok(n) => n,
+        Err(_) => match header_value.trim().chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse() {
+            Ok(n) => n,
+            Err(_) => return false,
+        },
+    };
+    comparator.apply(value, operand)
+}
+
+fn evaluate_date(rest: &str, header_value: &str) -> bool {
+    let date = match DateTime::parse_from_rfc2822(header_value.trim()) {
+        Ok(d) => d,
+        Err(_) => return false,
+    };
+
+    let mut parts = rest.splitn(2, char::is_whitespace);
+    let first = parts.next().unwrap_or("");
+    let remainder = parts.next().unwrap_or("").trim();
+
+    match first {
+        "year" | "month" | "day" | "hour" | "minute" | "second" | "weekday" => {
+            let mut parts = remainder.splitn(2, char::is_whitespace);
+            let comparator = match parts.next().and_then(Comparator::parse) {
+                Some(c) => c,
+                None => return false,
+            };
+            let operand: i64 = match parts.next().unwrap_or("").trim().parse() {
+                Ok(n) => n,
+                Err(_) => return false,
+            };
+            let actual: i64 = match first {
+                "year" => date.year() as i64,
+                "month" => date.month() as i64,
+                "day" => date.day() as i64,
+                "hour" => date.hour() as i64,
+                "minute" => date.minute() as i64,
+                "second" => date.second() as i64,
+                "weekday" => date.weekday().num_days_from_sunday() as i64,
+                _ => unreachable!(),
+            };
+            comparator.apply(actual, operand)
+        }
+        cmp_word => {
+            let comparator = match Comparator::parse(cmp_word) {
+                Some(c) => c,
+                None => return false,
+            };
+            let threshold = match NaiveDate::parse_from_str(remainder, "%Y-%m-%d") {
+                Ok(d) => d,
+                Err(_) => return false,
+            };
+            comparator.apply(date.date_naive(), threshold)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_relational_prefix() {
+        assert!(is_relational(":gt 100"));
+        assert!(is_relational("  :date year ge 2020"));
+        assert!(!is_relational("^Subject: invoice$"));
+    }
+
+    #[test]
+    fn numeric_comparators() {
+        assert!(evaluate(":gt 100", "150"));
+        assert!(!evaluate(":gt 100", "50"));
+        assert!(evaluate(":le 100", "100"));
+        assert!(evaluate(":ne 100", "99"));
+    }
+
+    #[test]
+    fn numeric_header_value_takes_leading_digits() {
+        assert!(evaluate(":gt 100", "150 bytes"));
+        assert!(!evaluate(":gt 100", "not-a-number"));
+    }
+
+    #[test]
+    fn unknown_comparator_or_bad_operand_fails_closed() {
+        assert!(!evaluate(":gtt 100", "150"));
+        assert!(!evaluate(":gt abc", "150"));
+    }
+
+    #[test]
+    fn date_field_comparators() {
+        let rfc2822 = "Tue, 1 Jul 2025 10:30:00 +0000";
+        assert!(evaluate(":date year ge 2020", rfc2822));
+        assert!(!evaluate(":date year ge 2030", rfc2822));
+        assert!(evaluate(":date month eq 7", rfc2822));
+    }
+
+    #[test]
+    fn date_literal_comparators() {
+        let rfc2822 = "Tue, 1 Jul 2025 10:30:00 +0000";
+        assert!(evaluate(":date ge 2020-01-01", rfc2822));
+        assert!(!evaluate(":date lt 2020-01-01", rfc2822));
+    }
+
+    #[test]
+    fn date_spec_fails_closed_on_bad_header_or_threshold() {
+        assert!(!evaluate(":date ge 2020-01-01", "not a date"));
+        assert!(!evaluate(":date ge not-a-date", "Tue, 1 Jul 2025 10:30:00 +0000"));
+    }
+
+    #[test]
+    fn validate_accepts_well_formed_specs() {
+        assert!(validate(":gt 100").is_ok());
+        assert!(validate(":date year ge 2020").is_ok());
+        assert!(validate(":date ge 2020-01-01").is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_malformed_specs() {
+        assert!(validate(":gtt 100").is_err());
+        assert!(validate(":gt abc").is_err());
+        assert!(validate(":date year gee 2020").is_err());
+        assert!(validate(":date ge not-a-date").is_err());
+    }
+}
@@ -0,0 +1,653 @@
+//! A small interpreter for a subset of the Sieve mail filtering language
+//! (RFC 5228), used as an alternative to the TOML `Rule` format.
+//!
+//! This covers the `if`/`elsif`/`else` control structure, the `header`,
+//! `address`, `exists` and `size` tests, the `allof`/`anyof`/`not`
+//! combinators, and the `keep`/`discard`/`fileinto`/`redirect`/`stop`
+//! actions. It does not attempt to implement the full RFC (extensions,
+//! `require`, comparators other than the default, etc).
+
+use mailparse::{MailHeaderMap, ParsedMail};
+use regex::Regex;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchType {
+    Is,
+    Contains,
+    Matches,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressPart {
+    All,
+    Local,
+    Domain,
+}
+
+#[derive(Debug, Clone)]
+pub enum Test {
+    Header {
+        match_type: MatchType,
+        names: Vec<String>,
+        keys: Vec<String>,
+    },
+    Address {
+        match_type: MatchType,
+        part: AddressPart,
+        names: Vec<String>,
+        keys: Vec<String>,
+    },
+    Exists(Vec<String>),
+    Size {
+        over: bool,
+        limit: u64,
+    },
+    AllOf(Vec<Test>),
+    AnyOf(Vec<Test>),
+    Not(Box<Test>),
+    True,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Keep,
+    Discard,
+    FileInto(String),
+    Redirect(String),
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    If(Vec<(Test, Vec<Command>)>, Vec<Command>),
+    Do(Action),
+}
+
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sieve parse error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Tag(String),
+    Str(String),
+    Number(u64),
+    Semicolon,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    let mut tokens = Vec::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' {
+            i += 1;
+            let mut s = String::new();
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                }
+                s.push(chars[i]);
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(ParseError("unterminated string literal".to_string()));
+            }
+            i += 1;
+            tokens.push(Token::Str(s));
+        } else if c == ';' {
+            tokens.push(Token::Semicolon);
+            i += 1;
+        } else if c == '{' {
+            tokens.push(Token::LBrace);
+            i += 1;
+        } else if c == '}' {
+            tokens.push(Token::RBrace);
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '[' {
+            tokens.push(Token::LBracket);
+            i += 1;
+        } else if c == ']' {
+            tokens.push(Token::RBracket);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else if c == ':' {
+            i += 1;
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if i == start {
+                return Err(ParseError("expected tag name after ':'".to_string()));
+            }
+            tokens.push(Token::Tag(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let mut n: u64 = chars[start..i]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .map_err(|_| ParseError("invalid number".to_string()))?;
+            if i < chars.len() {
+                n *= match chars[i].to_ascii_uppercase() {
+                    'K' => {
+                        i += 1;
+                        1024
+                    }
+                    'M' => {
+                        i += 1;
+                        1024 * 1024
+                    }
+                    'G' => {
+                        i += 1;
+                        1024 * 1024 * 1024
+                    }
+                    _ => 1,
+                };
+            }
+            tokens.push(Token::Number(n));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            return Err(ParseError(format!("unexpected character {:?}", c)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn expect(&mut self, tok: Token) -> Result<(), ParseError> {
+        match self.next() {
+            Some(t) if t == tok => Ok(()),
+            other => Err(ParseError(format!("expected {:?}, got {:?}", tok, other))),
+        }
+    }
+
+    fn parse_commands(&mut self, in_block: bool) -> Result<Vec<Command>, ParseError> {
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(Token::RBrace) if in_block => break,
+                Some(Token::Ident(name)) if name == "if" => out.push(self.parse_if()?),
+                Some(Token::Ident(_)) => out.push(Command::Do(self.parse_action()?)),
+                Some(t) => return Err(ParseError(format!("unexpected token {:?}", t))),
+            }
+        }
+        Ok(out)
+    }
+
+    fn parse_if(&mut self) -> Result<Command, ParseError> {
+        self.next(); // "if"
+        let mut branches = Vec::new();
+        let test = self.parse_test()?;
+        let body = self.parse_block()?;
+        branches.push((test, body));
+        loop {
+            match self.peek() {
+                Some(Token::Ident(name)) if name == "elsif" => {
+                    self.next();
+                    let test = self.parse_test()?;
+                    let body = self.parse_block()?;
+                    branches.push((test, body));
+                }
+                Some(Token::Ident(name)) if name == "else" => {
+                    self.next();
+                    let body = self.parse_block()?;
+                    return Ok(Command::If(branches, body));
+                }
+                _ => return Ok(Command::If(branches, Vec::new())),
+            }
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Command>, ParseError> {
+        self.expect(Token::LBrace)?;
+        let body = self.parse_commands(true)?;
+        self.expect(Token::RBrace)?;
+        Ok(body)
+    }
+
+    fn parse_action(&mut self) -> Result<Action, ParseError> {
+        let name = match self.next() {
+            Some(Token::Ident(n)) => n,
+            other => return Err(ParseError(format!("expected action, got {:?}", other))),
+        };
+        let action = match name.as_str() {
+            "keep" => Action::Keep,
+            "discard" => Action::Discard,
+            "stop" => Action::Stop,
+            "fileinto" => Action::FileInto(self.parse_single_string()?),
+            "redirect" => Action::Redirect(self.parse_single_string()?),
+            other => return Err(ParseError(format!("unknown action {:?}", other))),
+        };
+        self.expect(Token::Semicolon)?;
+        Ok(action)
+    }
+
+    fn parse_single_string(&mut self) -> Result<String, ParseError> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s),
+            other => Err(ParseError(format!("expected string, got {:?}", other))),
+        }
+    }
+
+    fn parse_string_list(&mut self) -> Result<Vec<String>, ParseError> {
+        match self.peek() {
+            Some(Token::Str(_)) => Ok(vec![self.parse_single_string()?]),
+            Some(Token::LBracket) => {
+                self.next();
+                let mut out = Vec::new();
+                loop {
+                    out.push(self.parse_single_string()?);
+                    match self.next() {
+                        Some(Token::Comma) => {}
+                        Some(Token::RBracket) => break,
+                        other => return Err(ParseError(format!("expected ',' or ']', got {:?}", other))),
+                    }
+                }
+                Ok(out)
+            }
+            other => Err(ParseError(format!("expected string or string list, got {:?}", other))),
+        }
+    }
+
+    fn parse_test_list(&mut self) -> Result<Vec<Test>, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut out = Vec::new();
+        loop {
+            out.push(self.parse_test()?);
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                other => return Err(ParseError(format!("expected ',' or ')', got {:?}", other))),
+            }
+        }
+        Ok(out)
+    }
+
+    fn parse_optional_match_type(&mut self) -> Result<MatchType, ParseError> {
+        if let Some(Token::Tag(t)) = self.peek() {
+            let match_type = match t.as_str() {
+                "is" => Some(MatchType::Is),
+                "contains" => Some(MatchType::Contains),
+                "matches" => Some(MatchType::Matches),
+                _ => None,
+            };
+            if let Some(match_type) = match_type {
+                self.next();
+                return Ok(match_type);
+            }
+        }
+        Ok(MatchType::Is)
+    }
+
+    fn parse_test(&mut self) -> Result<Test, ParseError> {
+        match self.next() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Test::True),
+                "not" => Ok(Test::Not(Box::new(self.parse_test()?))),
+                "allof" => Ok(Test::AllOf(self.parse_test_list()?)),
+                "anyof" => Ok(Test::AnyOf(self.parse_test_list()?)),
+                "header" => {
+                    let match_type = self.parse_optional_match_type()?;
+                    let names = self.parse_string_list()?;
+                    let keys = self.parse_string_list()?;
+                    Ok(Test::Header { match_type, names, keys })
+                }
+                "address" => {
+                    let mut part = AddressPart::All;
+                    let mut match_type = MatchType::Is;
+                    while let Some(Token::Tag(t)) = self.peek().cloned() {
+                        match t.as_str() {
+                            "all" => part = AddressPart::All,
+                            "localpart" => part = AddressPart::Local,
+                            "domain" => part = AddressPart::Domain,
+                            "is" => match_type = MatchType::Is,
+                            "contains" => match_type = MatchType::Contains,
+                            "matches" => match_type = MatchType::Matches,
+                            other => return Err(ParseError(format!("unknown tag :{}", other))),
+                        }
+                        self.next();
+                    }
+                    let names = self.parse_string_list()?;
+                    let keys = self.parse_string_list()?;
+                    Ok(Test::Address { match_type, part, names, keys })
+                }
+                "exists" => Ok(Test::Exists(self.parse_string_list()?)),
+                "size" => {
+                    let over = match self.next() {
+                        Some(Token::Tag(t)) if t == "over" => true,
+                        Some(Token::Tag(t)) if t == "under" => false,
+                        other => return Err(ParseError(format!("expected :over or :under, got {:?}", other))),
+                    };
+                    let limit = match self.next() {
+                        Some(Token::Number(n)) => n,
+                        other => return Err(ParseError(format!("expected number, got {:?}", other))),
+                    };
+                    Ok(Test::Size { over, limit })
+                }
+                other => Err(ParseError(format!("unknown test {:?}", other))),
+            },
+            other => Err(ParseError(format!("expected test, got {:?}", other))),
+        }
+    }
+}
+
+/// Parses a Sieve script into a list of top-level commands.
+pub fn parse(src: &str) -> Result<Vec<Command>, ParseError> {
+    let tokens = lex(src)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    parser.parse_commands(false)
+}
+
+/// Checks a parsed script for structural problems parsing alone can't catch
+/// (an empty script, or a `fileinto`/`redirect` with an empty target),
+/// mirroring how `Config::test()` already sanity-checks TOML rules beyond
+/// what `serde`/regex compilation catches.
+pub fn validate(commands: &[Command]) -> Result<(), String> {
+    if commands.is_empty() {
+        return Err("sieve script has no commands".to_string());
+    }
+    validate_commands(commands)
+}
+
+fn validate_commands(commands: &[Command]) -> Result<(), String> {
+    for command in commands {
+        match command {
+            Command::Do(Action::FileInto(path)) if path.is_empty() => {
+                return Err("fileinto with an empty folder path".to_string());
+            }
+            Command::Do(Action::Redirect(addr)) if addr.is_empty() => {
+                return Err("redirect with an empty address".to_string());
+            }
+            Command::Do(_) => {}
+            Command::If(branches, else_body) => {
+                for (_, body) in branches {
+                    validate_commands(body)?;
+                }
+                validate_commands(else_body)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn match_value(match_type: &MatchType, value: &str, key: &str) -> bool {
+    match match_type {
+        MatchType::Is => value.eq_ignore_ascii_case(key),
+        MatchType::Contains => value.to_lowercase().contains(&key.to_lowercase()),
+        MatchType::Matches => glob_match(value, key),
+    }
+}
+
+fn glob_match(value: &str, pattern: &str) -> bool {
+    let mut regex_str = String::from("(?i)^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex_str.push_str(".*"),
+            '?' => regex_str.push('.'),
+            c => regex_str.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex_str.push('$');
+    Regex::new(&regex_str).map(|re| re.is_match(value)).unwrap_or(false)
+}
+
+fn extract_addresses(value: &str) -> Vec<String> {
+    match mailparse::addrparse(value) {
+        Ok(list) => list
+            .into_inner()
+            .into_iter()
+            .flat_map(|addr| match addr {
+                mailparse::MailAddr::Single(info) => vec![info.addr],
+                mailparse::MailAddr::Group(group) => group.addrs.into_iter().map(|i| i.addr).collect(),
+            })
+            .collect(),
+        Err(_) => vec![value.to_string()],
+    }
+}
+
+fn address_part(addr: &str, part: &AddressPart) -> String {
+    match part {
+        AddressPart::All => addr.to_string(),
+        AddressPart::Local => addr.split('@').next().unwrap_or("").to_string(),
+        AddressPart::Domain => addr.split('@').nth(1).unwrap_or("").to_string(),
+    }
+}
+
+fn eval_test(test: &Test, parsed: &ParsedMail, size: u64) -> bool {
+    match test {
+        Test::True => true,
+        Test::Not(inner) => !eval_test(inner, parsed, size),
+        Test::AllOf(tests) => tests.iter().all(|t| eval_test(t, parsed, size)),
+        Test::AnyOf(tests) => tests.iter().any(|t| eval_test(t, parsed, size)),
+        Test::Exists(names) => names
+            .iter()
+            .all(|n| parsed.get_headers().get_first_value(n).is_some()),
+        Test::Size { over, limit } => {
+            if *over {
+                size > *limit
+            } else {
+                size < *limit
+            }
+        }
+        Test::Header { match_type, names, keys } => names.iter().any(|name| {
+            match parsed.get_headers().get_first_value(name) {
+                Some(value) => keys.iter().any(|key| match_value(match_type, &value, key)),
+                None => false,
+            }
+        }),
+        Test::Address { match_type, part, names, keys } => names.iter().any(|name| {
+            match parsed.get_headers().get_first_value(name) {
+                Some(value) => extract_addresses(&value).iter().any(|addr| {
+                    let candidate = address_part(addr, part);
+                    keys.iter().any(|key| match_value(match_type, &candidate, key))
+                }),
+                None => false,
+            }
+        }),
+    }
+}
+
+/// Walks the given commands against a parsed message, returning the actions
+/// that should be taken. `stop` halts evaluation of later commands, the way
+/// `handle()` stops at the first matching TOML rule.
+///
+/// If the script never reaches an explicit `keep`, `discard` or `fileinto`,
+/// the implicit `keep` from RFC 5228 section 2.10.2 is applied.
+pub fn evaluate(commands: &[Command], parsed: &ParsedMail, size: u64) -> Vec<Action> {
+    let mut actions = Vec::new();
+    run(commands, parsed, size, &mut actions);
+    let has_disposition = actions
+        .iter()
+        .any(|a| matches!(a, Action::Keep | Action::Discard | Action::FileInto(_)));
+    if !has_disposition {
+        actions.push(Action::Keep);
+    }
+    actions
+}
+
+fn run(commands: &[Command], parsed: &ParsedMail, size: u64, actions: &mut Vec<Action>) -> bool {
+    for command in commands {
+        match command {
+            Command::Do(Action::Stop) => return true,
+            Command::Do(action) => actions.push(action.clone()),
+            Command::If(branches, else_body) => {
+                let mut matched = false;
+                for (test, body) in branches {
+                    if eval_test(test, parsed, size) {
+                        matched = true;
+                        if run(body, parsed, size, actions) {
+                            return true;
+                        }
+                        break;
+                    }
+                }
+                if !matched && run(else_body, parsed, size, actions) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(script: &str, message: &[u8]) -> Vec<Action> {
+        let commands = parse(script).expect("script should parse");
+        let parsed = mailparse::parse_mail(message).expect("message should parse");
+        evaluate(&commands, &parsed, message.len() as u64)
+    }
+
+    #[test]
+    fn header_test_matches_and_fileinto_cancels_implicit_keep() {
+        let actions = eval(
+            r#"if header :contains "Subject" "invoice" { fileinto "Maildir/bills"; }"#,
+            b"Subject: your invoice is ready\r\n\r\nbody",
+        );
+        assert_eq!(actions, vec![Action::FileInto("Maildir/bills".to_string())]);
+    }
+
+    #[test]
+    fn implicit_keep_when_nothing_matches() {
+        let actions = eval(
+            r#"if header :contains "Subject" "invoice" { discard; }"#,
+            b"Subject: hello\r\n\r\nbody",
+        );
+        assert_eq!(actions, vec![Action::Keep]);
+    }
+
+    #[test]
+    fn stop_halts_later_commands() {
+        let actions = eval(
+            r#"discard; stop; fileinto "Maildir/unreachable";"#,
+            b"Subject: hello\r\n\r\nbody",
+        );
+        assert_eq!(actions, vec![Action::Discard]);
+    }
+
+    #[test]
+    fn address_test_matches_domain_part() {
+        let actions = eval(
+            r#"if address :domain :is "From" "example.com" { fileinto "Maildir/trusted"; }"#,
+            b"From: alice@example.com\r\n\r\nbody",
+        );
+        assert_eq!(actions, vec![Action::FileInto("Maildir/trusted".to_string())]);
+    }
+
+    #[test]
+    fn size_over_and_under() {
+        let commands = parse(r#"if size :over 10 { discard; }"#).unwrap();
+        let parsed = mailparse::parse_mail(b"Subject: x\r\n\r\nbody").unwrap();
+        assert_eq!(evaluate(&commands, &parsed, 20), vec![Action::Discard]);
+        assert_eq!(evaluate(&commands, &parsed, 5), vec![Action::Keep]);
+    }
+
+    #[test]
+    fn anyof_and_not_combinators() {
+        let actions = eval(
+            r#"if anyof (not exists "X-Nope", header :is "Subject" "hi") { discard; }"#,
+            b"Subject: hi\r\n\r\nbody",
+        );
+        assert_eq!(actions, vec![Action::Discard]);
+    }
+
+    #[test]
+    fn unknown_test_is_a_parse_error() {
+        assert!(parse(r#"if bogus "x" "y" { discard; }"#).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_empty_script() {
+        assert!(validate(&[]).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_empty_fileinto_and_redirect_targets() {
+        let commands = parse(r#"fileinto "";"#).unwrap();
+        assert!(validate(&commands).is_err());
+
+        let commands = parse(r#"redirect "";"#).unwrap();
+        assert!(validate(&commands).is_err());
+    }
+
+    #[test]
+    fn validate_recurses_into_if_branches() {
+        let commands = parse(r#"if header :is "Subject" "hi" { fileinto ""; }"#).unwrap();
+        assert!(validate(&commands).is_err());
+
+        let commands = parse(r#"if header :is "Subject" "hi" { discard; } else { redirect ""; }"#).unwrap();
+        assert!(validate(&commands).is_err());
+    }
+
+    #[test]
+    fn validate_accepts_a_well_formed_script() {
+        let commands = parse(r#"if header :is "Subject" "hi" { fileinto "Maildir/x"; } else { discard; }"#).unwrap();
+        assert!(validate(&commands).is_ok());
+    }
+}
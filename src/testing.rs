@@ -0,0 +1,177 @@
+//! Snapshot-based rule testing: `mailproc --test-dir <dir>` reads a
+//! directory of sample `.eml` files, each paired with a sibling `.expected`
+//! file describing which rule and actions the message should trigger, and
+//! diffs that against what `handle()` actually produces. Subprocess
+//! actions are never run here; only the matched rule/action list is
+//! compared.
+//!
+//! An `.expected` file is plain text with one directive per line:
+//!
+//! ```text
+//! rule: Subject
+//! action: @fileinto Maildir/work
+//! ```
+//!
+//! `rule:` is matched as a substring of the matched rule's `Display`
+//! output (or the literal `sieve`/`none`); `action:` lines must match the
+//! resulting action list in order.
+
+use crate::{handle, Config, Outcome};
+use std::fs;
+use std::path::Path;
+
+pub struct Expected {
+    pub rule: Option<String>,
+    pub actions: Vec<Vec<String>>,
+}
+
+pub fn parse_expected(text: &str) -> Expected {
+    let mut rule = None;
+    let mut actions = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("rule:") {
+            rule = Some(rest.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("action:") {
+            actions.push(rest.split_whitespace().map(str::to_string).collect());
+        }
+    }
+    Expected { rule, actions }
+}
+
+fn describe_outcome(outcome: &Option<(Outcome, Vec<u8>)>) -> String {
+    match outcome {
+        Some((Outcome::Rule(rule), _)) => format!("{}", rule),
+        Some((Outcome::Sieve(_), _)) => "sieve".to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn format_actions(actions: &[Vec<String>]) -> String {
+    actions.iter().map(|a| a.join(" ")).collect::<Vec<_>>().join("; ")
+}
+
+fn print_diff(label: &str, expected: &str, actual: &str) {
+    println!("  \x1b[31m- {}: {}\x1b[0m", label, expected);
+    println!("  \x1b[32m+ {}: {}\x1b[0m", label, actual);
+}
+
+/// Runs every `<name>.eml`/`<name>.expected` pair in `dir` against `config`,
+/// printing a pass/fail line (with a colored diff on failure) per file.
+/// Returns whether every fixture matched its expectation.
+pub fn run_test_dir(dir: impl AsRef<Path>, config: &Config) -> bool {
+    let dir = dir.as_ref();
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(it) => it.filter_map(Result::ok).collect(),
+        Err(e) => {
+            println!("Could not read test dir {:?}: {}", dir, e);
+            return false;
+        }
+    };
+    entries.sort_by_key(|e| e.path());
+
+    let mut all_ok = true;
+    for entry in entries {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("eml") {
+            continue;
+        }
+
+        let expected_text = match fs::read_to_string(path.with_extension("expected")) {
+            Ok(t) => t,
+            Err(_) => {
+                println!("{}: SKIP (no .expected file)", path.display());
+                continue;
+            }
+        };
+        let expected = parse_expected(&expected_text);
+
+        let raw = match fs::read(&path) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("{}: FAIL (could not read: {})", path.display(), e);
+                all_ok = false;
+                continue;
+            }
+        };
+        let parsed = match mailparse::parse_mail(&raw) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("{}: FAIL (could not parse: {})", path.display(), e);
+                all_ok = false;
+                continue;
+            }
+        };
+
+        let outcome = handle(&parsed, &raw, config);
+        let actual_rule = describe_outcome(&outcome);
+        let actual_actions = outcome.map(|(o, _)| o.actions()).unwrap_or_default();
+
+        let rule_ok = expected.rule.as_deref().is_none_or(|r| actual_rule.contains(r));
+        let actions_ok = expected.actions == actual_actions;
+
+        if rule_ok && actions_ok {
+            println!("{}: ok", path.display());
+        } else {
+            all_ok = false;
+            println!("{}: FAIL", path.display());
+            if !rule_ok {
+                print_diff("rule", expected.rule.as_deref().unwrap_or(""), &actual_rule);
+            }
+            if !actions_ok {
+                print_diff(
+                    "actions",
+                    &format_actions(&expected.actions),
+                    &format_actions(&actual_actions),
+                );
+            }
+        }
+    }
+    all_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixtures_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    #[test]
+    fn parse_expected_reads_rule_and_action_lines() {
+        let expected = parse_expected(
+            "# a comment\nrule: Subject\naction: @fileinto Maildir/bills\n",
+        );
+        assert_eq!(expected.rule.as_deref(), Some("Subject"));
+        assert_eq!(
+            expected.actions,
+            vec![vec!["@fileinto".to_string(), "Maildir/bills".to_string()]]
+        );
+    }
+
+    #[test]
+    fn run_test_dir_passes_its_own_fixtures() {
+        let config = Config::load_from_path(fixtures_dir().join("mailproc.conf"))
+            .expect("fixture config should load");
+        assert!(run_test_dir(fixtures_dir(), &config));
+    }
+
+    #[test]
+    fn run_test_dir_fails_a_wrong_expectation() {
+        let config = Config::load_from_path(fixtures_dir().join("mailproc.conf"))
+            .expect("fixture config should load");
+
+        let dir = std::env::temp_dir().join(format!("mailproc-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("could not create scratch test dir");
+        fs::copy(fixtures_dir().join("sample.eml"), dir.join("sample.eml")).unwrap();
+        fs::write(dir.join("sample.expected"), "action: @discard\n").unwrap();
+
+        assert!(!run_test_dir(&dir, &config));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
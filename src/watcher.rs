@@ -0,0 +1,140 @@
+//! Live config reloading for long-running invocations: `ConfigWatcher`
+//! polls the config file for modifications, re-parses it, runs it through
+//! `Config::test()`, and only swaps in the new `Config` if that validation
+//! passes. A broken edit is logged and the previous, working config stays
+//! active.
+
+use crate::Config;
+use log::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub struct ConfigWatcher {
+    config: Arc<RwLock<Config>>,
+}
+
+impl ConfigWatcher {
+    /// Loads `path` once (returning an error if that fails), then spawns a
+    /// background thread that re-checks its modification time every
+    /// `poll_interval` and hot-swaps in the new `Config` only if it passes
+    /// `Config::test()`.
+    pub fn spawn(path: PathBuf, poll_interval: Duration) -> Result<ConfigWatcher, Box<dyn std::error::Error>> {
+        let initial = Config::load_auto_from_path(&path)?;
+        let config = Arc::new(RwLock::new(initial));
+
+        let watched = Arc::clone(&config);
+        let mut last_modified = mtime(&path);
+        thread::spawn(move || loop {
+            thread::sleep(poll_interval);
+
+            let modified = mtime(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            match Config::load_auto_from_path(&path) {
+                Ok(new_config) if new_config.test() => {
+                    info!("Reloaded config from {:?}", path);
+                    *watched.write().expect("config lock poisoned") = new_config;
+                }
+                Ok(_) => error!("New config at {:?} failed validation, keeping previous config", path),
+                Err(e) => error!("Could not reload config from {:?}: {}", path, e),
+            }
+        });
+
+        Ok(ConfigWatcher { config })
+    }
+
+    /// A snapshot of the currently active, validated configuration.
+    pub fn current(&self) -> Config {
+        self.config.read().expect("config lock poisoned").clone()
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+    const MESSAGE: &[u8] = b"Subject: hi\r\n\r\nbody";
+
+    fn scratch_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mailproc-watcher-test-{}-{}.conf", name, std::process::id()));
+        std::fs::write(&path, contents).expect("could not write scratch config");
+        path
+    }
+
+    fn fileinto_target(config: &Config) -> Option<String> {
+        let parsed = mailparse::parse_mail(MESSAGE).unwrap();
+        let (outcome, _) = handle(&parsed, MESSAGE, config)?;
+        outcome.actions().into_iter().find_map(|action| {
+            if action.first().map(String::as_str) == Some("@fileinto") {
+                action.get(1).cloned()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Waits until `condition` is true or a couple of seconds pass, checking
+    /// it every 20ms (100 checks in all).
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn reloads_config_when_the_file_changes() {
+        let path = scratch_config(
+            "reload",
+            "version = 1\n\n[[rules]]\naction = [[\"@fileinto\", \"Maildir/one\"]]\nheaders = [{ Subject = \"hi\" }]\n",
+        );
+        let watcher = ConfigWatcher::spawn(path.clone(), POLL_INTERVAL).expect("should load initial config");
+        assert_eq!(fileinto_target(&watcher.current()).as_deref(), Some("Maildir/one"));
+
+        std::fs::write(
+            &path,
+            "version = 1\n\n[[rules]]\naction = [[\"@fileinto\", \"Maildir/two\"]]\nheaders = [{ Subject = \"hi\" }]\n",
+        )
+        .unwrap();
+
+        let reloaded = wait_until(|| fileinto_target(&watcher.current()).as_deref() == Some("Maildir/two"));
+        assert!(reloaded, "watcher did not pick up the updated config in time");
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn keeps_previous_config_when_reload_fails_validation() {
+        let path = scratch_config(
+            "invalid",
+            "version = 1\n\n[[rules]]\naction = [[\"@fileinto\", \"Maildir/one\"]]\nheaders = [{ Subject = \"hi\" }]\n",
+        );
+        let watcher = ConfigWatcher::spawn(path.clone(), POLL_INTERVAL).expect("should load initial config");
+
+        std::fs::write(
+            &path,
+            "version = 1\n\n[[rules]]\naction = [[\"totally-bogus-program-that-does-not-exist\"]]\nheaders = [{ Subject = \"hi\" }]\n",
+        )
+        .unwrap();
+
+        // Give the background thread several poll cycles to (not) reload.
+        thread::sleep(POLL_INTERVAL * 10);
+        assert_eq!(fileinto_target(&watcher.current()).as_deref(), Some("Maildir/one"));
+
+        std::fs::remove_file(&path).ok();
+    }
+}